@@ -0,0 +1,102 @@
+use std::fmt;
+use std::io;
+
+/// Errors produced while decoding or encoding bencode.
+///
+/// Unlike a bare `io::Error`, this distinguishes a genuine I/O failure
+/// (`Io`) from malformed input (`Syntax`) and premature end of stream
+/// (`Eof`), so callers parsing large `.torrent` files can tell corruption
+/// apart from a flaky read and locate it via the reported byte offset.
+#[derive(Debug)]
+pub enum Error {
+	Io(io::Error),
+	Syntax { msg: &'static str, offset: usize },
+	Eof,
+	/// An error from the serde layer rather than from the byte-level
+	/// decoder/encoder: raised by a caller's `serde::Serialize`/`Deserialize`
+	/// impl (e.g. a missing struct field), or by this crate's `Serializer`/
+	/// `Deserializer` for a value with no bencode mapping (a float, a `None`)
+	/// or one that does not fit the requested Rust type.
+	Custom(String),
+}
+
+impl Error {
+	pub fn is_io(&self) -> bool {
+		match *self {
+			Error::Io(_) => true,
+			_ => false,
+		}
+	}
+
+	pub fn is_syntax(&self) -> bool {
+		match *self {
+			Error::Syntax { .. } => true,
+			_ => false,
+		}
+	}
+
+	pub fn is_eof(&self) -> bool {
+		match *self {
+			Error::Eof => true,
+			_ => false,
+		}
+	}
+
+	pub fn is_custom(&self) -> bool {
+		match *self {
+			Error::Custom(_) => true,
+			_ => false,
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Error::Io(ref err) => write!(f, "{}", err),
+			Error::Syntax { msg, offset } => write!(f, "{} at offset {}", msg, offset),
+			Error::Eof => write!(f, "unexpected end of input"),
+			Error::Custom(ref msg) => write!(f, "{}", msg),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+	fn from(err: io::Error) -> Error {
+		if err.kind() == io::ErrorKind::UnexpectedEof {
+			Error::Eof
+		} else {
+			Error::Io(err)
+		}
+	}
+}
+
+impl From<Error> for io::Error {
+	fn from(err: Error) -> io::Error {
+		match err {
+			Error::Io(err) => err,
+			Error::Eof => io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of input"),
+			Error::Syntax { msg, offset } => io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("{} at offset {}", msg, offset),
+			),
+			Error::Custom(msg) => io::Error::new(io::ErrorKind::InvalidData, msg),
+		}
+	}
+}
+
+impl serde::de::Error for Error {
+	fn custom<T: fmt::Display>(msg: T) -> Error {
+		Error::Custom(msg.to_string())
+	}
+}
+
+impl serde::ser::Error for Error {
+	fn custom<T: fmt::Display>(msg: T) -> Error {
+		Error::Custom(msg.to_string())
+	}
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
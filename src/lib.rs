@@ -1,300 +1,431 @@
+extern crate num;
+extern crate serde;
+
+mod error;
+mod serde_support;
+
 use std::collections::HashMap;
 
 use std::io;
-use std::io::{Error, ErrorKind};
+use std::io::{Read, Write};
+
+use num::bigint::BigInt;
+use num::{ToPrimitive, Zero};
+
+pub use error::{Error, Result};
+pub use serde_support::{from_bytes, to_bytes, Deserializer, Serializer};
+
+/// A byte range in the original input a value was decoded from.
+pub type Span = std::ops::Range<usize>;
 
 #[derive(Debug, PartialEq)]
 pub enum BencodeValue {
-	Integer(i64),
+	Integer(BigInt),
 	String(Vec<u8>),
 	List(Vec<BencodeValue>),
 	Dictionary(HashMap<Vec<u8>, BencodeValue>),
-	EndOfFile,
 }
 
-pub fn parse_value(iter: &mut Iterator<Item = io::Result<u8>>) -> io::Result<BencodeValue> {
-	let mut iter = iter.peekable();
+/// Streams bencode values out of `read`, one `next()` call at a time.
+///
+/// Internally this keeps a one-byte "primed" buffer so `peek` can look
+/// ahead without consuming, and a running byte `index` so parse errors can
+/// say where in the stream they occurred.
+pub struct Decoder<R> {
+	read: R,
+	primed: Option<u8>,
+	index: usize,
+}
 
-	loop {
-		let byte;
-		match iter.peek() {
-			Some(result) => match *result {
-				Ok(val) => {
-					byte = val;
-				}
-				Err(ref err) => {
-					println!("Error while reading file {}", err);
-					return Err(Error::new(ErrorKind::Other, "Error while reading file"));
-				}
-			},
-			_ => break,
+impl<R: Read> Decoder<R> {
+	pub fn new(read: R) -> Decoder<R> {
+		Decoder {
+			read,
+			primed: None,
+			index: 0,
 		}
+	}
+
+	/// Number of bytes consumed from the underlying reader so far.
+	pub fn position(&self) -> usize {
+		self.index
+	}
+
+	/// Reads the next top-level bencode value, or `None` once the stream
+	/// is exhausted.
+	pub fn next(&mut self) -> Result<Option<BencodeValue>> {
+		let byte = match self.peek()? {
+			Some(byte) => byte,
+			None => return Ok(None),
+		};
 
 		match byte {
-			0x30...0x39 => return Ok(BencodeValue::String(parse_string(&mut iter)?)),
-			0x64 => return Ok(BencodeValue::Dictionary(parse_dict(&mut iter)?)),
-			0x69 => return Ok(BencodeValue::Integer(parse_int(&mut iter)?)),
-			0x6c => return Ok(BencodeValue::List(parse_list(&mut iter)?)),
-			val => {
-				return Err(Error::new(
-					ErrorKind::InvalidData,
-					format!("Unexpected byte {}", val),
-				))
-			}
+			0x30...0x39 => Ok(Some(BencodeValue::String(self.read_string()?))),
+			0x64 => Ok(Some(BencodeValue::Dictionary(self.read_dict()?))),
+			0x69 => Ok(Some(BencodeValue::Integer(self.read_int()?))),
+			0x6c => Ok(Some(BencodeValue::List(self.read_list()?))),
+			_ => Err(self.syntax_error("Unexpected byte")),
 		}
 	}
 
-	return Ok(BencodeValue::EndOfFile);
-}
+	/// Like `next`, but a `None` (premature end of stream) is itself an
+	/// error; used where a value is known to be required, e.g. inside a
+	/// list or dictionary.
+	fn read_value(&mut self) -> Result<BencodeValue> {
+		match self.next()? {
+			Some(val) => Ok(val),
+			None => Err(Error::Eof),
+		}
+	}
+
+	fn read_string(&mut self) -> Result<Vec<u8>> {
+		let mut digits = Vec::new();
+
+		loop {
+			let curr_byte = self.expect()?;
+			if curr_byte >= 0x30 && curr_byte <= 0x39 {
+				digits.push(curr_byte);
+			} else if curr_byte == 0x3a {
+				break;
+			} else {
+				// the offending byte was already consumed, so it sits at index - 1
+				return Err(Error::Syntax {
+					msg: "Expected an integer (byte 0x30 - 0x39)",
+					offset: self.index - 1,
+				});
+			}
+		}
 
-fn parse_string(iter: &mut Iterator<Item = io::Result<u8>>) -> io::Result<Vec<u8>> {
-	let mut ret = Vec::new();
+		let length = vec_to_int(&digits, false);
+		let length = length
+			.to_usize()
+			.ok_or_else(|| self.syntax_error("String length does not fit into memory"))?;
+		let mut ret = Vec::with_capacity(length);
 
-	loop {
-		let curr_byte = iter.next().ok_or(Error::new(
-			ErrorKind::InvalidData,
-			"File ended while reading string",
-		))??;
-		if curr_byte >= 0x30 && curr_byte <= 0x39 {
+		for _ in 0..length {
+			let curr_byte = self.expect()?;
 			ret.push(curr_byte);
-		} else if curr_byte == 0x3a {
-			break;
-		} else {
-			return Err(Error::new(
-				ErrorKind::InvalidData,
-				format!("Expected an integer (byte 0x30 - 0x39) got {:x}", curr_byte),
-			));
 		}
+
+		Ok(ret)
 	}
 
-	let length = vec_to_int(&ret, false)?;
-	let mut ret = Vec::with_capacity(length as usize); //TODO fix potential overflow
+	fn read_dict(&mut self) -> Result<HashMap<Vec<u8>, BencodeValue>> {
+		self.skip_indicator(0x64)?; // we don't need the "start of dictionary" indicator
+
+		let mut ret = HashMap::new();
 
-	for _ in 0..length {
-		let curr_byte = iter.next().ok_or(Error::new(
-			ErrorKind::InvalidData,
-			"File ended while reading string.",
-		))??;
-		ret.push(curr_byte);
+		loop {
+			//TODO handle empty dict "de"
+			let key = self.read_string()?;
+			let value = self.read_value()?;
+			ret.insert(key, value);
+
+			match self.peek()? {
+				Some(0x65) => {
+					self.skip();
+					break;
+				}
+				Some(_) => {}
+				None => return Err(Error::Eof),
+			}
+		}
+
+		Ok(ret)
 	}
 
-	return Ok(ret);
-}
+	/// Like `read_dict`, but also records the byte span each value was
+	/// decoded from. Re-encoding a decoded value isn't guaranteed to
+	/// reproduce the original bytes (whitespace-free bencode has none, but
+	/// canonicalization can still reorder keys), so callers that need the
+	/// *exact* original bytes of a nested value — e.g. to hash a torrent's
+	/// `info` dictionary — slice the source with the returned span instead.
+	pub fn read_dict_spanned(&mut self) -> Result<HashMap<Vec<u8>, (BencodeValue, Span)>> {
+		self.skip_indicator(0x64)?; // we don't need the "start of dictionary" indicator
+
+		let mut ret = HashMap::new();
+
+		loop {
+			//TODO handle empty dict "de"
+			let key = self.read_string()?;
+			let start = self.index;
+			let value = self.read_value()?;
+			let end = self.index;
+			ret.insert(key, (value, start..end));
+
+			match self.peek()? {
+				Some(0x65) => {
+					self.skip();
+					break;
+				}
+				Some(_) => {}
+				None => return Err(Error::Eof),
+			}
+		}
 
-fn parse_dict(
-	iter: &mut Iterator<Item = io::Result<u8>>,
-) -> io::Result<HashMap<Vec<u8>, BencodeValue>> {
-	let mut iter = iter.peekable();
-	iter.next(); // we don't need the "start of dictionary" indicator
-
-	let mut ret = HashMap::new();
-
-	loop {
-		//TODO handle empty dict "de"
-		let key = parse_string(&mut iter)?;
-		let value = parse_value(&mut iter)?;
-		//println!("Adding k:{:?} v:{:?} to dictionary", key, value);
-		ret.insert(key, value);
-
-		let test = iter.peek().ok_or(Error::new(
-			ErrorKind::InvalidData,
-			"File ended while reading dictionary",
-		))?;
-		match *test {
-			Ok(val) if val == 0x65 => break,
-			Ok(_) => {}
-			Err(ref err) => {
-				println!("Error while reading dictionary {}", err);
-				return Err(Error::new(
-					ErrorKind::Other,
-					"Error while reading dictionary",
-				));
+		Ok(ret)
+	}
+
+	/// Whether another top-level value remains to be read.
+	pub fn has_more(&mut self) -> Result<bool> {
+		Ok(self.peek()?.is_some())
+	}
+
+	/// Parses an bencode list. The format is l<bencoded values>e for example
+	///
+	/// l4:spam4:eggse represents the list of two strings: [ "spam", "eggs" ]
+	/// le represents an empty list: []
+	fn read_list(&mut self) -> Result<Vec<BencodeValue>> {
+		self.skip_indicator(0x6c)?; // we don't need the "start of list" indicator
+
+		if let Some(0x65) = self.peek()? {
+			// empty list
+			self.skip();
+			return Ok(Vec::new());
+		}
+
+		let mut ret = Vec::new();
+
+		loop {
+			let val = self.read_value()?;
+			ret.push(val);
+
+			match self.peek()? {
+				Some(0x65) => {
+					self.skip();
+					break;
+				}
+				Some(_) => {}
+				None => return Err(Error::Eof),
 			}
 		}
+
+		Ok(ret)
 	}
 
-	return Ok(ret);
-}
+	/// Parses an bencode integer value. The format is
+	/// i<integer encoded in base ten ASCII>e we allowe some integers that are
+	/// forbidden by the specification. for example
+	///
+	/// i-0e // -0 is forbidden
+	/// i0123e // leading zeroes are forbidden (except i0e)
+	///
+	/// the spec places no limit on the size of an integer, so we parse into a
+	/// BigInt rather than capping out at 64 bits.
+	fn read_int(&mut self) -> Result<BigInt> {
+		self.skip_indicator(0x69)?; // we don't need the "start of integer" indicator
+
+		let mut is_negative = false;
+		let mut curr_byte = self.expect()?;
+
+		if curr_byte == 0x2d {
+			is_negative = true;
+			curr_byte = self.expect()?;
+		}
 
-/// Parses an bencode list. The format is l<bencoded values>e for example
-///
-/// l4:spam4:eggse represents the list of two strings: [ "spam", "eggs" ]
-/// le represents an empty list: []
-fn parse_list(iter: &mut Iterator<Item = io::Result<u8>>) -> io::Result<Vec<BencodeValue>> {
-	let mut iter = iter.peekable();
-	iter.next(); // we don't need the "start of list" indicator
-
-	if let Some(Ok(0x65)) = iter.peek() { // empty list
-		return Ok(Vec::new()) // TODO use empty vec
-	}
-
-	let mut ret = Vec::new();
-
-	loop {
-		let val = parse_value(&mut iter)?;
-		//println!("Adding {:?} to list", val);
-		ret.push(val);
-
-		let test = iter.peek().ok_or(Error::new(
-			ErrorKind::InvalidData,
-			"File ended while reading list",
-		))?;
-		match *test {
-			Ok(val) if val == 0x65 => break,
-			Ok(_) => {}
-			Err(ref err) => {
-				println!("Error while reading list {}", err);
-				return Err(Error::new(ErrorKind::Other, "Error while reading list"));
+		let mut int_chars = Vec::new();
+
+		loop {
+			if curr_byte == 0x65 {
+				break;
 			}
+			if curr_byte >= 0x30 && curr_byte <= 0x39 {
+				int_chars.push(curr_byte)
+			} else {
+				// the offending byte was already consumed, so it sits at index - 1
+				return Err(Error::Syntax {
+					msg: "Expected an integer (byte 0x30 - 0x39)",
+					offset: self.index - 1,
+				});
+			}
+			curr_byte = self.expect()?;
 		}
+
+		Ok(vec_to_int(&int_chars, is_negative))
 	}
 
-	return Ok(ret);
-}
+	/// Looks at the next byte without consuming it.
+	fn peek(&mut self) -> Result<Option<u8>> {
+		if self.primed.is_none() {
+			let mut buf = [0u8; 1];
+			let n = self.read.read(&mut buf)?;
+			self.primed = if n == 0 { None } else { Some(buf[0]) };
+		}
+		Ok(self.primed)
+	}
 
-/// Parses an bencode integer value. The format is
-/// i<integer encoded in base ten ASCII>e we allowe some integers that are
-/// forbidden by the specification. for example
-///
-/// i-0e // -0 is forbidden
-/// i0123e // leading zeroes are forbidden (except i0e)
-///
-/// while the size of an integer is not stated in the spec we support up to 64 bits
-fn parse_int(iter: &mut Iterator<Item = io::Result<u8>>) -> io::Result<i64> {
-	let max_digits = 19;
-
-	iter.next(); // we don't need the "start of integer" indicator
-
-	let mut is_negative = false;
-	let mut curr_byte;
-
-	curr_byte = iter.next().ok_or(Error::new(
-		ErrorKind::InvalidData,
-		"File ended while reading integer.",
-	))??;
-
-	if curr_byte == 0x2d {
-		is_negative = true;
-		curr_byte = iter.next().ok_or(Error::new(
-			ErrorKind::InvalidData,
-			"File ended while reading integer.",
-		))??;
-	}
-
-	let mut int_chars = Vec::with_capacity(19);
-
-	loop {
-		if int_chars.len() >= max_digits {
-			return Err(Error::new(
-				ErrorKind::InvalidData,
-				"Integer is larger than 64 bytes.",
-			));
-		} else if curr_byte == 0x65 {
-			break;
+	/// Discards the byte previously returned by `peek`, advancing `index`.
+	fn skip(&mut self) {
+		if self.primed.take().is_some() {
+			self.index += 1;
 		}
-		if curr_byte >= 0x30 && curr_byte <= 0x39 {
-			int_chars.push(curr_byte)
-		} else {
-			return Err(Error::new(
-				ErrorKind::InvalidData,
-				format!("Expected an integer (byte 0x30 - 0x39) got {:x}", curr_byte),
-			));
+	}
+
+	/// Reads and consumes the next byte.
+	fn read(&mut self) -> Result<Option<u8>> {
+		let byte = self.peek()?;
+		self.skip();
+		Ok(byte)
+	}
+
+	/// Discards the type indicator byte (`i`/`l`/`d`) a container parser is
+	/// called on. Unlike a bare `skip`, this peeks first so it works
+	/// whether or not the indicator was already primed by `next`. `expected`
+	/// is checked so calling a parser directly (as the tests do, bypassing
+	/// `next`'s dispatch) on the wrong kind of value fails loudly instead of
+	/// silently misreading the first content byte as the indicator.
+	fn skip_indicator(&mut self, expected: u8) -> Result<()> {
+		match self.peek()? {
+			Some(byte) if byte == expected => {
+				self.skip();
+				Ok(())
+			}
+			Some(_) => Err(self.syntax_error("Unexpected type indicator")),
+			None => Err(Error::Eof),
 		}
-		curr_byte = iter.next().ok_or(Error::new(
-			ErrorKind::InvalidData,
-			"File ended while reading integer.",
-		))??;
 	}
 
-	return Ok(vec_to_int(&int_chars, is_negative)?);
+	/// Like `read`, but a `None` (premature end of stream) is reported as
+	/// `Error::Eof`.
+	fn expect(&mut self) -> Result<u8> {
+		self.read()?.ok_or(Error::Eof)
+	}
+
+	fn syntax_error(&self, msg: &'static str) -> Error {
+		Error::Syntax {
+			msg,
+			offset: self.index,
+		}
+	}
 }
 
-/// Parses the number given as ASCII in vec to an i64. Does not support
+/// Parses the number given as ASCII in vec to a BigInt. Does not support
 /// a sign. The sign must be passed via the is_negative parameter.
-fn vec_to_int(vec: &Vec<u8>, is_negative: bool) -> io::Result<i64> {
-	let mut ret: i64 = 0;
+fn vec_to_int(vec: &Vec<u8>, is_negative: bool) -> BigInt {
+	let mut ret = BigInt::zero();
+	let ten = BigInt::from(10);
 
 	for val in vec {
-		if let Some(i) = ret.checked_mul(10) {
-			ret = i;
-		} else {
-			return Err(Error::new(
-				ErrorKind::InvalidData,
-				"Integer field is longer i64",
-			));
-		}
-		if let Some(i) = if is_negative {
-			ret.checked_sub(*val as i64 - 0x30)
-		} else {
-			ret.checked_add(*val as i64 - 0x30)
-		} {
-			ret = i;
-		} else {
-			return Err(Error::new(
-				ErrorKind::InvalidData,
-				"Integer field is larger than i64",
-			));
-		}
+		let digit = BigInt::from(*val as i64 - 0x30);
+		ret = ret * &ten;
+		ret = if is_negative { ret - digit } else { ret + digit };
+	}
+
+	ret
+}
+
+/// Serializes `value` to canonical bencode, writing the result to `out`.
+///
+/// Canonical here means dictionary keys are emitted sorted by raw byte
+/// value, integers never carry a leading zero or a `-0`, matching what
+/// a conforming decoder (and BitTorrent clients hashing an `info` dict)
+/// expects to read back.
+pub fn write_value(value: &BencodeValue, out: &mut impl Write) -> io::Result<()> {
+	match value {
+		BencodeValue::Integer(val) => write_int(val, out),
+		BencodeValue::String(val) => write_string(val, out),
+		BencodeValue::List(val) => write_list(val, out),
+		BencodeValue::Dictionary(val) => write_dict(val, out),
 	}
+}
+
+/// Convenience wrapper around `write_value` for callers that just want the
+/// encoded bytes rather than a `Write` to stream them into.
+pub fn to_vec(value: &BencodeValue) -> io::Result<Vec<u8>> {
+	let mut out = Vec::new();
+	write_value(value, &mut out)?;
+	Ok(out)
+}
+
+fn write_int(val: &BigInt, out: &mut impl Write) -> io::Result<()> {
+	write!(out, "i{}e", val)
+}
+
+pub(crate) fn write_string(val: &Vec<u8>, out: &mut impl Write) -> io::Result<()> {
+	write!(out, "{}:", val.len())?;
+	out.write_all(val)
+}
 
-	Ok(ret)
+fn write_list(val: &Vec<BencodeValue>, out: &mut impl Write) -> io::Result<()> {
+	out.write_all(b"l")?;
+	for item in val {
+		write_value(item, out)?;
+	}
+	out.write_all(b"e")
+}
+
+fn write_dict(val: &HashMap<Vec<u8>, BencodeValue>, out: &mut impl Write) -> io::Result<()> {
+	let mut entries: Vec<(&Vec<u8>, &BencodeValue)> = val.iter().collect();
+	entries.sort_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b));
+
+	out.write_all(b"d")?;
+	for (key, value) in entries {
+		write_string(key, out)?;
+		write_value(value, out)?;
+	}
+	out.write_all(b"e")
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use std::io::Cursor;
+
+	fn decoder_for(input: &str) -> Decoder<Cursor<Vec<u8>>> {
+		Decoder::new(Cursor::new(input.as_bytes().to_vec()))
+	}
 
 	#[test]
 	fn test_vec_to_int_positive() {
 		assert_eq!(
-			vec_to_int(&vec!['1' as u8, '2' as u8, '3' as u8], false).unwrap(),
-			123
+			vec_to_int(&vec!['1' as u8, '2' as u8, '3' as u8], false),
+			BigInt::from(123)
 		);
 	}
 
 	#[test]
 	fn test_vec_to_int_negative() {
 		assert_eq!(
-			vec_to_int(&vec!['1' as u8, '2' as u8, '3' as u8], true).unwrap(),
-			-123
+			vec_to_int(&vec!['1' as u8, '2' as u8, '3' as u8], true),
+			BigInt::from(-123)
 		);
 	}
 
 	#[test]
 	fn test_vec_to_int_zero_prefix_positive() {
 		assert_eq!(
-			vec_to_int(&vec!['0' as u8, '2' as u8, '3' as u8], false).unwrap(),
-			23
+			vec_to_int(&vec!['0' as u8, '2' as u8, '3' as u8], false),
+			BigInt::from(23)
 		);
 	}
 
 	#[test]
 	fn test_vec_to_int_zero_prefix_negative() {
 		assert_eq!(
-			vec_to_int(&vec!['0' as u8, '2' as u8, '3' as u8], true).unwrap(),
-			-23
+			vec_to_int(&vec!['0' as u8, '2' as u8, '3' as u8], true),
+			BigInt::from(-23)
 		);
 	}
 
 	#[test]
 	fn test_vec_to_int_zero_positive() {
 		assert_eq!(
-			vec_to_int(&vec!['0' as u8, '0' as u8, '0' as u8], false).unwrap(),
-			0
+			vec_to_int(&vec!['0' as u8, '0' as u8, '0' as u8], false),
+			BigInt::zero()
 		);
 	}
 
 	#[test]
 	fn test_vec_to_int_zero_negative() {
 		assert_eq!(
-			vec_to_int(&vec!['0' as u8, '0' as u8, '0' as u8], true).unwrap(),
-			-0
+			vec_to_int(&vec!['0' as u8, '0' as u8, '0' as u8], true),
+			BigInt::zero()
 		);
 	}
 
 	#[test]
 	fn test_vec_to_int_empty() {
-		assert_eq!(vec_to_int(&vec![], true).unwrap(), 0);
+		assert_eq!(vec_to_int(&vec![], true), BigInt::zero());
 	}
 
 	#[test]
@@ -307,14 +438,16 @@ mod tests {
 					'7' as u8, '5' as u8, '8' as u8, '0' as u8, '7' as u8,
 				],
 				false,
-			).unwrap(),
-			9223372036854775807
+			),
+			BigInt::from(i64::max_value())
 		);
 	}
 
 	#[test]
-	fn test_vec_to_int_max_i64_and_one_overflow() {
-		assert!(
+	fn test_vec_to_int_beyond_i64_max() {
+		// one more than i64::MAX used to overflow the old i64 accumulator;
+		// BigInt has no such ceiling.
+		assert_eq!(
 			vec_to_int(
 				&vec![
 					'9' as u8, '2' as u8, '2' as u8, '3' as u8, '3' as u8, '7' as u8, '2' as u8,
@@ -322,7 +455,8 @@ mod tests {
 					'7' as u8, '5' as u8, '8' as u8, '0' as u8, '8' as u8,
 				],
 				false,
-			).is_err()
+			),
+			BigInt::from(i64::max_value()) + BigInt::from(1)
 		);
 	}
 
@@ -336,14 +470,16 @@ mod tests {
 					'7' as u8, '5' as u8, '8' as u8, '0' as u8, '8' as u8,
 				],
 				true,
-			).unwrap(),
-			-9223372036854775808
+			),
+			BigInt::from(i64::min_value())
 		);
 	}
 
 	#[test]
-	fn test_vec_to_int_min_i64_and_minus_one_underflow() {
-		assert!(
+	fn test_vec_to_int_beyond_i64_min() {
+		// one less than i64::MIN used to underflow the old i64 accumulator;
+		// BigInt has no such floor.
+		assert_eq!(
 			vec_to_int(
 				&vec![
 					'9' as u8, '2' as u8, '2' as u8, '3' as u8, '3' as u8, '7' as u8, '2' as u8,
@@ -351,118 +487,174 @@ mod tests {
 					'7' as u8, '5' as u8, '8' as u8, '0' as u8, '9' as u8,
 				],
 				true,
-			).is_err()
+			),
+			BigInt::from(i64::min_value()) - BigInt::from(1)
 		);
 	}
 
 	#[test]
-	fn test_parse_int_positive() {
-		let mut val = String::from("i123e")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert_eq!(parse_int(&mut val).unwrap(), 123);
+	fn test_vec_to_int_well_beyond_i64_max() {
+		let digits: Vec<u8> = "99999999999999999999999999999999999999"
+			.bytes()
+			.collect();
+		let expected: BigInt = "99999999999999999999999999999999999999".parse().unwrap();
+		assert_eq!(vec_to_int(&digits, false), expected);
 	}
 
 	#[test]
-	fn test_parse_int_negative() {
-		let mut val = String::from("i-123e")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert_eq!(parse_int(&mut val).unwrap(), -123);
+	fn test_read_int_positive() {
+		let mut dec = decoder_for("i123e");
+		assert_eq!(dec.read_int().unwrap(), BigInt::from(123));
 	}
 
 	#[test]
-	fn test_parse_int_zero_prefix_positive() {
+	fn test_read_int_negative() {
+		let mut dec = decoder_for("i-123e");
+		assert_eq!(dec.read_int().unwrap(), BigInt::from(-123));
+	}
+
+	#[test]
+	fn test_read_int_zero_prefix_positive() {
 		// leading zeroes are illegal according to the spec. Not sure if we should care
 		// but since we don't error out make sure the result is at least "correct"
-		let mut val = String::from("i0123e")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert_eq!(parse_int(&mut val).unwrap(), 123);
+		let mut dec = decoder_for("i0123e");
+		assert_eq!(dec.read_int().unwrap(), BigInt::from(123));
 	}
 
 	#[test]
-	fn test_parse_int_zero_prefix_negative() {
+	fn test_read_int_zero_prefix_negative() {
 		// leading zeroes are illegal according to the spec. Not sure if we should care
 		// but since we don't error out make sure the result is at least "correct"
-		let mut val = String::from("i-0123e")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert_eq!(parse_int(&mut val).unwrap(), -123);
+		let mut dec = decoder_for("i-0123e");
+		assert_eq!(dec.read_int().unwrap(), BigInt::from(-123));
 	}
 
 	#[test]
-	fn test_parse_int_zero_positive() {
-		let mut val = String::from("i0e")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert_eq!(parse_int(&mut val).unwrap(), 0);
+	fn test_read_int_zero_positive() {
+		let mut dec = decoder_for("i0e");
+		assert_eq!(dec.read_int().unwrap(), BigInt::zero());
 	}
 
 	#[test]
-	fn test_parse_int_zero_negative() {
+	fn test_read_int_zero_negative() {
 		// negative zero is illegal according to the spec. Not sure if we should care
 		// but since we don't error out make sure the result is at least "correct"
-		let mut val = String::from("i-0e")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert_eq!(parse_int(&mut val).unwrap(), -0);
-		let mut val = String::from("i-0e")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert_eq!(parse_int(&mut val).unwrap(), 0);
+		let mut dec = decoder_for("i-0e");
+		assert_eq!(dec.read_int().unwrap(), BigInt::zero());
 	}
 
 	#[test]
-	fn test_parse_int_empty() {
+	fn test_read_int_empty() {
 		// not sure if this is legal. The spec says that integers have no size limitation whatever
 		// that means regarding to empty integers. Treating them as 0 seems sensible.
-		let mut val = String::from("ie").into_bytes().into_iter().map(|byte| {
-			return Ok(byte);
-		});
-		assert_eq!(parse_int(&mut val).unwrap(), 0);
+		let mut dec = decoder_for("ie");
+		assert_eq!(dec.read_int().unwrap(), BigInt::zero());
+	}
+
+	#[test]
+	fn test_read_int_double_start() {
+		let mut dec = decoder_for("ii123e");
+		assert!(dec.read_int().is_err());
+	}
+
+	#[test]
+	fn test_read_int_invalid_digit() {
+		let mut dec = decoder_for("i12x3e");
+		assert!(dec.read_int().is_err());
 	}
 
 	#[test]
-	fn test_parse_int_double_start() {
-		let mut val = String::from("ii123e")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert!(parse_int(&mut val).is_err());
+	fn test_read_int_missing_end() {
+		let mut dec = decoder_for("i123");
+		assert!(dec.read_int().is_err());
 	}
 
 	#[test]
-	fn test_parse_int_invalid_digit() {
-		let mut val = String::from("i12x3e")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert!(parse_int(&mut val).is_err());
+	fn test_read_int_well_beyond_i64_max() {
+		let mut dec = decoder_for("i99999999999999999999999999999999999999e");
+		let expected: BigInt = "99999999999999999999999999999999999999".parse().unwrap();
+		assert_eq!(dec.read_int().unwrap(), expected);
 	}
 
 	#[test]
-	fn test_parse_int_missing_end() {
-		let mut val = String::from("i123")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert!(parse_int(&mut val).is_err());
+	fn test_read_int_error_reports_offset() {
+		let mut dec = decoder_for("i12x3e");
+		let err = dec.read_int().unwrap_err();
+		assert!(err.to_string().contains("offset 3"));
+	}
+
+	#[test]
+	fn test_read_list_empty() {
+		let mut dec = decoder_for("le");
+		assert!(dec.read_list().is_ok());
+	}
+
+	#[test]
+	fn test_next_on_empty_stream() {
+		let mut dec = decoder_for("");
+		assert_eq!(dec.next().unwrap(), None);
+	}
+
+	#[test]
+	fn test_next_iterates_top_level_values() {
+		let mut dec = decoder_for("i1ei2ei3e");
+		assert_eq!(dec.next().unwrap(), Some(BencodeValue::Integer(BigInt::from(1))));
+		assert_eq!(dec.next().unwrap(), Some(BencodeValue::Integer(BigInt::from(2))));
+		assert_eq!(dec.next().unwrap(), Some(BencodeValue::Integer(BigInt::from(3))));
+		assert_eq!(dec.next().unwrap(), None);
+	}
+
+	#[test]
+	fn test_write_value_integer() {
+		assert_eq!(to_vec(&BencodeValue::Integer(BigInt::from(123))).unwrap(), b"i123e");
+		assert_eq!(to_vec(&BencodeValue::Integer(BigInt::from(-123))).unwrap(), b"i-123e");
+		assert_eq!(to_vec(&BencodeValue::Integer(BigInt::zero())).unwrap(), b"i0e");
+	}
+
+	#[test]
+	fn test_write_value_string() {
+		let val = BencodeValue::String(b"spam".to_vec());
+		assert_eq!(to_vec(&val).unwrap(), b"4:spam");
+	}
+
+	#[test]
+	fn test_write_value_list() {
+		let val = BencodeValue::List(vec![
+			BencodeValue::String(b"spam".to_vec()),
+			BencodeValue::String(b"eggs".to_vec()),
+		]);
+		assert_eq!(to_vec(&val).unwrap(), b"l4:spam4:eggse");
+	}
+
+	#[test]
+	fn test_write_value_list_empty() {
+		assert_eq!(to_vec(&BencodeValue::List(Vec::new())).unwrap(), b"le");
+	}
+
+	#[test]
+	fn test_write_value_dict_sorts_keys() {
+		let mut dict = HashMap::new();
+		dict.insert(b"spam".to_vec(), BencodeValue::Integer(BigInt::from(1)));
+		dict.insert(b"cow".to_vec(), BencodeValue::Integer(BigInt::from(2)));
+
+		let val = BencodeValue::Dictionary(dict);
+		assert_eq!(to_vec(&val).unwrap(), b"d3:cowi2e4:spami1ee");
+	}
+
+	#[test]
+	fn test_write_value_dict_empty() {
+		assert_eq!(
+			to_vec(&BencodeValue::Dictionary(HashMap::new())).unwrap(),
+			b"de"
+		);
 	}
 
 	#[test]
-	fn test_parse_list_empty() {
-		let mut val = String::from("le")
-			.into_bytes()
-			.into_iter()
-			.map(|byte| Ok(byte));
-		assert!(parse_list(&mut val).is_ok());
+	fn test_write_value_roundtrip() {
+		// "foo" sorts before "spam", so the re-encoded dict reorders the keys
+		// even though the input didn't put them in canonical order.
+		let mut dec = decoder_for("d4:spaml1:a1:be3:fooi42ee");
+		let parsed = dec.next().unwrap().unwrap();
+		assert_eq!(to_vec(&parsed).unwrap(), b"d3:fooi42e4:spaml1:a1:bee");
 	}
 }
@@ -1,9 +1,12 @@
 extern crate bencode;
+extern crate num;
+extern crate sha1;
 
-use bencode::BencodeValue;
+use bencode::{BencodeValue, Decoder};
+use num::ToPrimitive;
+use sha1::{Digest, Sha1};
 use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
+use std::io::{Cursor, Read};
 use std::{env, process};
 
 // Maximal allowed size for .torrent files in bytes
@@ -14,7 +17,26 @@ struct Metainfo {
 	name: String,
 	piece_length: i64, // should be unsigned
 	pieces_hash: Vec<u8>,
-	length: Option<i64>
+	mode: Mode,
+	info_hash: [u8; 20],
+}
+
+/// Whether an info dict describes a single file or a directory tree.
+///
+/// A bencode info dict carries exactly one of `length` (single-file) or
+/// `files` (multi-file); we validate that up front and keep the two shapes
+/// apart here rather than leaving them as a pair of `Option`s that could
+/// both be set or both be empty.
+#[derive(Debug)]
+enum Mode {
+	Single { length: i64 },
+	Multi { files: Vec<FileEntry> },
+}
+
+#[derive(Debug)]
+struct FileEntry {
+	length: i64,
+	path: Vec<String>,
 }
 
 fn main() {
@@ -30,7 +52,7 @@ fn run_app() -> i32 {
 		return 1;
 	}
 	let path = args.next().expect("Missing FILENAME argument");
-	let f = match File::open(&path) {
+	let mut f = match File::open(&path) {
 		Ok(file) => file,
 		Err(err) => {
 			println!("Error while opening {}\n{}", &path, err);
@@ -38,12 +60,26 @@ fn run_app() -> i32 {
 		}
 	};
 
-	let mut bytes = BufReader::new(f).bytes();
+	let mut raw = Vec::new();
+	if let Err(err) = f.read_to_end(&mut raw) {
+		println!("Error while reading {}\n{}", &path, err);
+		return 1;
+	}
+
+	let mut decoder = Decoder::new(Cursor::new(&raw[..]));
 
 	loop {
-		match bencode::parse_value(&mut bytes) {
-			Ok(BencodeValue::EndOfFile) => break,
-			Ok(val) => println!("{:#?}", bencode_to_metainfo(val).unwrap()),
+		match decoder.has_more() {
+			Ok(false) => break,
+			Ok(true) => {}
+			Err(ref err) => {
+				println!("{}", err);
+				return 1;
+			}
+		}
+
+		match decoder.read_dict_spanned() {
+			Ok(dict) => println!("{:#?}", bencode_to_metainfo(dict, &raw).unwrap()),
 			Err(ref err) => {
 				println!("{}", err);
 				return 1;
@@ -54,16 +90,14 @@ fn run_app() -> i32 {
 	0
 }
 
-fn bencode_to_metainfo(val: BencodeValue) -> Result<Metainfo, String> {
-	let mut dict = if let BencodeValue::Dictionary(d) = val {
-		d
-	} else {
-		return Err(String::from("val must be of type Dictionary"));
-	};
-
+fn bencode_to_metainfo(
+	mut dict: std::collections::HashMap<Vec<u8>, (BencodeValue, bencode::Span)>,
+	raw: &[u8],
+) -> Result<Metainfo, String> {
 	let announce = dict
 		.remove(&String::from("announce").into_bytes())
-		.ok_or_else(|| "Missing announce element")?;
+		.ok_or_else(|| "Missing announce element")?
+		.0;
 	let announce = if let BencodeValue::String(announce) = announce {
 		announce
 	} else {
@@ -72,7 +106,7 @@ fn bencode_to_metainfo(val: BencodeValue) -> Result<Metainfo, String> {
 	let announce = String::from_utf8_lossy(&announce);
 
 
-	let info = dict
+	let (info, info_span) = dict
 		.remove(&String::from("info").into_bytes())
 		.ok_or_else(|| "Missing info element")?;
 	let mut info = if let BencodeValue::Dictionary(info) = info {
@@ -81,6 +115,12 @@ fn bencode_to_metainfo(val: BencodeValue) -> Result<Metainfo, String> {
 		return Err(String::from("val must be of type Dictionary"));
 	};
 
+	let info_hash = {
+		let mut hasher = Sha1::new();
+		hasher.update(&raw[info_span]);
+		hasher.finalize().into()
+	};
+
 	let name = info.remove(&String::from("name").into_bytes()).ok_or_else(|| "Missing name element")?;
 	let name = if let BencodeValue::String(name) = name {
 		name
@@ -91,7 +131,7 @@ fn bencode_to_metainfo(val: BencodeValue) -> Result<Metainfo, String> {
 
 	let piece_length = info.remove(&String::from("piece length").into_bytes()).ok_or_else(|| "Missing name element")?;
 	let piece_length = if let BencodeValue::Integer(piece_length) = piece_length {
-		piece_length
+		piece_length.to_i64().ok_or_else(|| "piece length does not fit in an i64")?
 	} else {
 		return Err(String::from("name must be String"));
 	};
@@ -104,25 +144,135 @@ fn bencode_to_metainfo(val: BencodeValue) -> Result<Metainfo, String> {
 	};
 
 	let length = info.remove(&String::from("length").into_bytes());
-	let length = if length.is_some() {
-		if let BencodeValue::Integer(length) = length.unwrap() {
-			Option::Some(length)
-		} else {
-			return Err(String::from("length must be Integer"));
+	let files = info.remove(&String::from("files").into_bytes());
+
+	let mode = match (length, files) {
+		(Some(length), None) => {
+			let length = if let BencodeValue::Integer(length) = length {
+				length.to_i64().ok_or_else(|| "length does not fit in an i64")?
+			} else {
+				return Err(String::from("length must be Integer"));
+			};
+			Mode::Single { length }
 		}
-	} else {
-		Option::None
+		(None, Some(files)) => {
+			let files = if let BencodeValue::List(files) = files {
+				files
+			} else {
+				return Err(String::from("files must be List"));
+			};
+			let files = files
+				.into_iter()
+				.map(bencode_to_file_entry)
+				.collect::<Result<Vec<_>, _>>()?;
+			Mode::Multi { files }
+		}
+		(Some(_), Some(_)) => return Err(String::from("info dict must not have both length and files")),
+		(None, None) => return Err(String::from("info dict must have either length or files")),
 	};
 
-	if length.is_none() {
-		let _files = info.remove(&String::from("files").into_bytes());
-	}
-
 	Ok(Metainfo {
 		announce: announce.to_string(),
 		name: name.to_string(),
 		piece_length,
 		pieces_hash,
-		length
+		mode,
+		info_hash,
 	})
 }
+
+fn bencode_to_file_entry(val: BencodeValue) -> Result<FileEntry, String> {
+	let mut entry = if let BencodeValue::Dictionary(entry) = val {
+		entry
+	} else {
+		return Err(String::from("file entry must be of type Dictionary"));
+	};
+
+	let length = entry
+		.remove(&String::from("length").into_bytes())
+		.ok_or_else(|| "Missing length element")?;
+	let length = if let BencodeValue::Integer(length) = length {
+		length.to_i64().ok_or_else(|| "length does not fit in an i64")?
+	} else {
+		return Err(String::from("length must be Integer"));
+	};
+
+	let path = entry
+		.remove(&String::from("path").into_bytes())
+		.ok_or_else(|| "Missing path element")?;
+	let path = if let BencodeValue::List(path) = path {
+		path
+	} else {
+		return Err(String::from("path must be List"));
+	};
+	let path = path
+		.into_iter()
+		.map(|component| {
+			if let BencodeValue::String(component) = component {
+				Ok(String::from_utf8_lossy(&component).to_string())
+			} else {
+				Err(String::from("path component must be String"))
+			}
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	Ok(FileEntry { length, path })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bencode::Decoder;
+	use std::io::Cursor;
+
+	// A minimal single-file .torrent whose info dict's sha1 is known
+	// (computed independently with Python's hashlib for this test).
+	const SINGLE_FILE_TORRENT: &[u8] = b"d8:announce27:http://example.com/announce4:infod6:lengthi12345e4:name8:test.txt12:piece lengthi16384e6:pieces20:01234567890123456789ee";
+
+	const MULTI_FILE_TORRENT: &[u8] = b"d8:announce27:http://example.com/announce4:infod5:filesld6:lengthi100e4:pathl5:a.txteed6:lengthi200e4:pathl3:dir5:b.txteee4:name8:test-dir12:piece lengthi16384e6:pieces20:01234567890123456789ee";
+
+	fn metainfo_for(torrent: &[u8]) -> Metainfo {
+		let mut decoder = Decoder::new(Cursor::new(torrent));
+		let dict = decoder.read_dict_spanned().unwrap();
+		bencode_to_metainfo(dict, torrent).unwrap()
+	}
+
+	#[test]
+	fn test_info_hash_matches_known_torrent() {
+		let metainfo = metainfo_for(SINGLE_FILE_TORRENT);
+
+		assert_eq!(
+			metainfo.info_hash,
+			[
+				0xb2, 0xec, 0x9b, 0xa7, 0x2e, 0x55, 0xd7, 0x87, 0x7a, 0x1b, 0x0e, 0x6d, 0xac, 0x96,
+				0x6f, 0xdd, 0x95, 0x15, 0x89, 0x1c,
+			]
+		);
+	}
+
+	#[test]
+	fn test_single_file_torrent_parses_as_single_mode() {
+		let metainfo = metainfo_for(SINGLE_FILE_TORRENT);
+
+		match metainfo.mode {
+			Mode::Single { length } => assert_eq!(length, 12345),
+			Mode::Multi { .. } => panic!("expected Mode::Single"),
+		}
+	}
+
+	#[test]
+	fn test_multi_file_torrent_parses_file_list() {
+		let metainfo = metainfo_for(MULTI_FILE_TORRENT);
+
+		match metainfo.mode {
+			Mode::Multi { files } => {
+				assert_eq!(files.len(), 2);
+				assert_eq!(files[0].length, 100);
+				assert_eq!(files[0].path, vec![String::from("a.txt")]);
+				assert_eq!(files[1].length, 200);
+				assert_eq!(files[1].path, vec![String::from("dir"), String::from("b.txt")]);
+			}
+			Mode::Single { .. } => panic!("expected Mode::Multi"),
+		}
+	}
+}
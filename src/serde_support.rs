@@ -0,0 +1,906 @@
+//! Maps bencode values to Rust types via `serde::Serialize`/`Deserialize`,
+//! so callers can `#[derive(Deserialize)]` a struct like `Metainfo` instead
+//! of hand-walking a `HashMap<Vec<u8>, BencodeValue>`.
+//!
+//! Like most self-describing formats, the `Deserializer` side works by
+//! parsing a `BencodeValue` up front (via the existing `Decoder`) and then
+//! handing a visitor over that value; the `Serializer` side writes straight
+//! to the underlying `Write`, buffering only what canonical output requires
+//! (dictionary entries, to sort them by key).
+
+use std::io::{Read, Write};
+
+use num::bigint::BigInt;
+use num::{ToPrimitive, Zero};
+use serde::de::{self, Visitor};
+use serde::ser::{self, Serialize};
+
+use crate::error::{Error, Result};
+use crate::{write_string, BencodeValue, Decoder};
+
+/// Deserializes `T` from a single top-level bencode value read from `read`.
+pub struct Deserializer<R> {
+	decoder: Decoder<R>,
+}
+
+impl<R: Read> Deserializer<R> {
+	pub fn new(read: R) -> Deserializer<R> {
+		Deserializer {
+			decoder: Decoder::new(read),
+		}
+	}
+
+	/// Reads and deserializes the next top-level value.
+	pub fn deserialize<T: de::DeserializeOwned>(&mut self) -> Result<T> {
+		let value = self.decoder.next()?.ok_or(Error::Eof)?;
+		T::deserialize(ValueDeserializer(value))
+	}
+}
+
+/// Convenience wrapper around `Deserializer` for callers that already have
+/// the full input in memory.
+pub fn from_bytes<T: de::DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+	Deserializer::new(bytes).deserialize()
+}
+
+/// Serializes `T` to canonical bencode, writing the result to `out`.
+pub struct Serializer<W> {
+	out: W,
+}
+
+impl<W: Write> Serializer<W> {
+	pub fn new(out: W) -> Serializer<W> {
+		Serializer { out }
+	}
+}
+
+/// Convenience wrapper around `Serializer` for callers that just want the
+/// encoded bytes rather than a `Write` to stream them into.
+pub fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+	let mut out = Vec::new();
+	value.serialize(&mut Serializer::new(&mut out))?;
+	Ok(out)
+}
+
+// --- deserialization ---------------------------------------------------
+
+/// A serde `Deserializer` driven by an already-parsed `BencodeValue` rather
+/// than a byte stream; used for the top-level value as well as every
+/// nested list item and dict entry.
+struct ValueDeserializer(BencodeValue);
+
+fn int_error(n: &BigInt) -> Error {
+	Error::Custom(format!("integer {} does not fit into the requested type", n))
+}
+
+fn not_utf8(_: std::string::FromUtf8Error) -> Error {
+	Error::Custom("expected a UTF-8 string".to_string())
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+	type Error = Error;
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::Integer(n) => match n.to_i64() {
+				Some(n) => visitor.visit_i64(n),
+				None => match n.to_u64() {
+					Some(n) => visitor.visit_u64(n),
+					None => Err(int_error(&n)),
+				},
+			},
+			BencodeValue::String(bytes) => visitor.visit_byte_buf(bytes),
+			BencodeValue::List(items) => visitor.visit_seq(SeqDeserializer {
+				iter: items.into_iter(),
+			}),
+			BencodeValue::Dictionary(map) => visitor.visit_map(MapDeserializer {
+				iter: map.into_iter(),
+				value: None,
+			}),
+		}
+	}
+
+	fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::Integer(n) => visitor.visit_bool(!n.is_zero()),
+			_ => Err(Error::Custom("expected an integer 0/1 for a bool".to_string())),
+		}
+	}
+
+	fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_i64(visitor)
+	}
+
+	fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_i64(visitor)
+	}
+
+	fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_i64(visitor)
+	}
+
+	fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::Integer(n) => visitor.visit_i64(n.to_i64().ok_or_else(|| int_error(&n))?),
+			_ => Err(Error::Custom("expected an integer".to_string())),
+		}
+	}
+
+	fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_u64(visitor)
+	}
+
+	fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_u64(visitor)
+	}
+
+	fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_u64(visitor)
+	}
+
+	fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::Integer(n) => visitor.visit_u64(n.to_u64().ok_or_else(|| int_error(&n))?),
+			_ => Err(Error::Custom("expected an integer".to_string())),
+		}
+	}
+
+	fn deserialize_f32<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+		Err(Error::Custom("bencode has no float representation".to_string()))
+	}
+
+	fn deserialize_f64<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+		Err(Error::Custom("bencode has no float representation".to_string()))
+	}
+
+	fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::String(bytes) => {
+				let s = String::from_utf8(bytes).map_err(not_utf8)?;
+				let mut chars = s.chars();
+				match (chars.next(), chars.next()) {
+					(Some(c), None) => visitor.visit_char(c),
+					_ => Err(Error::Custom("expected a single-character string".to_string())),
+				}
+			}
+			_ => Err(Error::Custom("expected a string".to_string())),
+		}
+	}
+
+	fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::String(bytes) => visitor.visit_string(String::from_utf8(bytes).map_err(not_utf8)?),
+			_ => Err(Error::Custom("expected a string".to_string())),
+		}
+	}
+
+	fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_str(visitor)
+	}
+
+	fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::String(bytes) => visitor.visit_byte_buf(bytes),
+			_ => Err(Error::Custom("expected a string".to_string())),
+		}
+	}
+
+	fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_bytes(visitor)
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		// Bencode has no null; a present key is always `Some`. Absent
+		// optional fields never reach this deserializer; serde's derived
+		// struct impl fills them in as `None`.
+		visitor.visit_some(self)
+	}
+
+	fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		visitor.visit_unit()
+	}
+
+	fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+		self.deserialize_unit(visitor)
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+		visitor.visit_newtype_struct(self)
+	}
+
+	fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::List(items) => visitor.visit_seq(SeqDeserializer {
+				iter: items.into_iter(),
+			}),
+			_ => Err(Error::Custom("expected a list".to_string())),
+		}
+	}
+
+	fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+		self.deserialize_seq(visitor)
+	}
+
+	fn deserialize_tuple_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value> {
+		self.deserialize_seq(visitor)
+	}
+
+	fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::Dictionary(map) => visitor.visit_map(MapDeserializer {
+				iter: map.into_iter(),
+				value: None,
+			}),
+			_ => Err(Error::Custom("expected a dictionary".to_string())),
+		}
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value> {
+		self.deserialize_map(visitor)
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_variants: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value> {
+		match self.0 {
+			BencodeValue::String(bytes) => {
+				let variant = String::from_utf8(bytes).map_err(not_utf8)?;
+				visitor.visit_enum(de::value::StringDeserializer::<Error>::new(variant))
+			}
+			BencodeValue::Dictionary(mut map) => {
+				if map.len() != 1 {
+					return Err(Error::Custom(
+						"expected a single-entry dict for an enum variant".to_string(),
+					));
+				}
+				let (key, value) = map.drain().next().unwrap();
+				let variant = String::from_utf8(key).map_err(not_utf8)?;
+				visitor.visit_enum(EnumDeserializer { variant, value })
+			}
+			_ => Err(Error::Custom(
+				"expected a string or single-entry dict for an enum".to_string(),
+			)),
+		}
+	}
+
+	fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_str(visitor)
+	}
+
+	fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_any(visitor)
+	}
+}
+
+struct SeqDeserializer {
+	iter: std::vec::IntoIter<BencodeValue>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqDeserializer {
+	type Error = Error;
+
+	fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+		match self.iter.next() {
+			Some(value) => seed.deserialize(ValueDeserializer(value)).map(Some),
+			None => Ok(None),
+		}
+	}
+
+	fn size_hint(&self) -> Option<usize> {
+		Some(self.iter.len())
+	}
+}
+
+struct MapDeserializer {
+	iter: std::collections::hash_map::IntoIter<Vec<u8>, BencodeValue>,
+	value: Option<BencodeValue>,
+}
+
+impl<'de> de::MapAccess<'de> for MapDeserializer {
+	type Error = Error;
+
+	fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+		match self.iter.next() {
+			Some((key, value)) => {
+				self.value = Some(value);
+				seed.deserialize(ValueDeserializer(BencodeValue::String(key))).map(Some)
+			}
+			None => Ok(None),
+		}
+	}
+
+	fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+		let value = self
+			.value
+			.take()
+			.expect("next_value_seed called before next_key_seed");
+		seed.deserialize(ValueDeserializer(value))
+	}
+
+	fn size_hint(&self) -> Option<usize> {
+		Some(self.iter.len())
+	}
+}
+
+/// Drives the `{variant: content}` dict representation of non-unit enum
+/// variants; `ValueDeserializer` itself plays the role of `VariantAccess`
+/// since unwrapping the content is just deserializing the inner value.
+struct EnumDeserializer {
+	variant: String,
+	value: BencodeValue,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+	type Error = Error;
+	type Variant = ValueDeserializer;
+
+	fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant)> {
+		let variant = seed.deserialize(de::value::StringDeserializer::<Error>::new(self.variant))?;
+		Ok((variant, ValueDeserializer(self.value)))
+	}
+}
+
+impl<'de> de::VariantAccess<'de> for ValueDeserializer {
+	type Error = Error;
+
+	fn unit_variant(self) -> Result<()> {
+		Ok(())
+	}
+
+	fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+		seed.deserialize(self)
+	}
+
+	fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+		de::Deserializer::deserialize_tuple(self, len, visitor)
+	}
+
+	fn struct_variant<V: Visitor<'de>>(
+		self,
+		fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value> {
+		de::Deserializer::deserialize_struct(self, "", fields, visitor)
+	}
+}
+
+// --- serialization -------------------------------------------------------
+
+impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+	type Ok = ();
+	type Error = Error;
+	type SerializeSeq = SeqSerializer<'a, W>;
+	type SerializeTuple = SeqSerializer<'a, W>;
+	type SerializeTupleStruct = SeqSerializer<'a, W>;
+	type SerializeTupleVariant = SeqSerializer<'a, W>;
+	type SerializeMap = MapSerializer<'a, W>;
+	type SerializeStruct = MapSerializer<'a, W>;
+	type SerializeStructVariant = MapSerializer<'a, W>;
+
+	fn serialize_bool(self, v: bool) -> Result<()> {
+		self.serialize_i64(v as i64)
+	}
+
+	fn serialize_i8(self, v: i8) -> Result<()> {
+		self.serialize_i64(v as i64)
+	}
+
+	fn serialize_i16(self, v: i16) -> Result<()> {
+		self.serialize_i64(v as i64)
+	}
+
+	fn serialize_i32(self, v: i32) -> Result<()> {
+		self.serialize_i64(v as i64)
+	}
+
+	fn serialize_i64(self, v: i64) -> Result<()> {
+		write!(self.out, "i{}e", v).map_err(Error::from)
+	}
+
+	fn serialize_u8(self, v: u8) -> Result<()> {
+		self.serialize_u64(v as u64)
+	}
+
+	fn serialize_u16(self, v: u16) -> Result<()> {
+		self.serialize_u64(v as u64)
+	}
+
+	fn serialize_u32(self, v: u32) -> Result<()> {
+		self.serialize_u64(v as u64)
+	}
+
+	fn serialize_u64(self, v: u64) -> Result<()> {
+		write!(self.out, "i{}e", v).map_err(Error::from)
+	}
+
+	fn serialize_f32(self, _v: f32) -> Result<()> {
+		Err(Error::Custom("bencode has no float representation".to_string()))
+	}
+
+	fn serialize_f64(self, _v: f64) -> Result<()> {
+		Err(Error::Custom("bencode has no float representation".to_string()))
+	}
+
+	fn serialize_char(self, v: char) -> Result<()> {
+		self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
+	}
+
+	fn serialize_str(self, v: &str) -> Result<()> {
+		self.serialize_bytes(v.as_bytes())
+	}
+
+	fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+		write_string(&v.to_vec(), &mut self.out).map_err(Error::from)
+	}
+
+	fn serialize_none(self) -> Result<()> {
+		Err(Error::Custom(
+			"bencode has no null representation; skip the field instead (e.g. with skip_serializing_if)".to_string(),
+		))
+	}
+
+	fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
+		value.serialize(self)
+	}
+
+	fn serialize_unit(self) -> Result<()> {
+		Err(Error::Custom("bencode has no unit representation".to_string()))
+	}
+
+	fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+		self.serialize_unit()
+	}
+
+	fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<()> {
+		self.serialize_str(variant)
+	}
+
+	fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<()> {
+		value.serialize(self)
+	}
+
+	fn serialize_newtype_variant<T: ?Sized + Serialize>(
+		self,
+		_name: &'static str,
+		_index: u32,
+		variant: &'static str,
+		value: &T,
+	) -> Result<()> {
+		let mut buf = Vec::new();
+		value.serialize(&mut Serializer::new(&mut buf))?;
+		write_sorted_dict(&mut self.out, vec![(variant.as_bytes().to_vec(), buf)])
+	}
+
+	fn serialize_seq(self, _len: Option<usize>) -> Result<SeqSerializer<'a, W>> {
+		self.out.write_all(b"l").map_err(Error::from)?;
+		Ok(SeqSerializer { out: &mut self.out })
+	}
+
+	fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'a, W>> {
+		self.serialize_seq(Some(len))
+	}
+
+	fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer<'a, W>> {
+		self.serialize_seq(Some(len))
+	}
+
+	fn serialize_tuple_variant(
+		self,
+		_name: &'static str,
+		_index: u32,
+		variant: &'static str,
+		_len: usize,
+	) -> Result<SeqSerializer<'a, W>> {
+		// Written as `d<variant>l...ee`; `SerializeTupleVariant::end` closes
+		// both the list and the wrapping dict.
+		self.out.write_all(b"d").map_err(Error::from)?;
+		write_string(&variant.as_bytes().to_vec(), &mut self.out).map_err(Error::from)?;
+		self.out.write_all(b"l").map_err(Error::from)?;
+		Ok(SeqSerializer { out: &mut self.out })
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer<'a, W>> {
+		Ok(MapSerializer {
+			out: &mut self.out,
+			entries: Vec::new(),
+			next_key: None,
+			wrap_variant: None,
+		})
+	}
+
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<MapSerializer<'a, W>> {
+		self.serialize_map(None)
+	}
+
+	fn serialize_struct_variant(
+		self,
+		_name: &'static str,
+		_index: u32,
+		variant: &'static str,
+		_len: usize,
+	) -> Result<MapSerializer<'a, W>> {
+		Ok(MapSerializer {
+			out: &mut self.out,
+			entries: Vec::new(),
+			next_key: None,
+			wrap_variant: Some(variant),
+		})
+	}
+}
+
+/// Backs `SerializeSeq`/`SerializeTuple`/etc.; list elements are written
+/// straight through in order, since unlike dict keys a list's element
+/// order never needs reordering for canonical output.
+pub struct SeqSerializer<'a, W> {
+	out: &'a mut W,
+}
+
+impl<'a, W: Write> ser::SerializeSeq for SeqSerializer<'a, W> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+		value.serialize(&mut Serializer::new(&mut *self.out))
+	}
+
+	fn end(self) -> Result<()> {
+		self.out.write_all(b"e").map_err(Error::from)
+	}
+}
+
+impl<'a, W: Write> ser::SerializeTuple for SeqSerializer<'a, W> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+		ser::SerializeSeq::serialize_element(self, value)
+	}
+
+	fn end(self) -> Result<()> {
+		ser::SerializeSeq::end(self)
+	}
+}
+
+impl<'a, W: Write> ser::SerializeTupleStruct for SeqSerializer<'a, W> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+		ser::SerializeSeq::serialize_element(self, value)
+	}
+
+	fn end(self) -> Result<()> {
+		ser::SerializeSeq::end(self)
+	}
+}
+
+impl<'a, W: Write> ser::SerializeTupleVariant for SeqSerializer<'a, W> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+		ser::SerializeSeq::serialize_element(self, value)
+	}
+
+	fn end(self) -> Result<()> {
+		// close the list, then the dict wrapping {variant: [...]}
+		self.out.write_all(b"ee").map_err(Error::from)
+	}
+}
+
+/// Dictionary entries are buffered so they can be written out sorted by
+/// key, matching `write_dict`'s canonical ordering.
+pub struct MapSerializer<'a, W> {
+	out: &'a mut W,
+	entries: Vec<(Vec<u8>, Vec<u8>)>,
+	next_key: Option<Vec<u8>>,
+	wrap_variant: Option<&'static str>,
+}
+
+impl<'a, W: Write> MapSerializer<'a, W> {
+	fn finish(self) -> Result<()> {
+		match self.wrap_variant {
+			Some(variant) => {
+				let mut inner = Vec::new();
+				write_sorted_dict(&mut inner, self.entries)?;
+				write_sorted_dict(self.out, vec![(variant.as_bytes().to_vec(), inner)])
+			}
+			None => write_sorted_dict(self.out, self.entries),
+		}
+	}
+}
+
+impl<'a, W: Write> ser::SerializeMap for MapSerializer<'a, W> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+		let mut buf = Vec::new();
+		key.serialize(&mut MapKeySerializer { out: &mut buf })?;
+		self.next_key = Some(buf);
+		Ok(())
+	}
+
+	fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+		let key = self.next_key.take().expect("serialize_value called before serialize_key");
+		let mut buf = Vec::new();
+		value.serialize(&mut Serializer::new(&mut buf))?;
+		self.entries.push((key, buf));
+		Ok(())
+	}
+
+	fn end(self) -> Result<()> {
+		self.finish()
+	}
+}
+
+impl<'a, W: Write> ser::SerializeStruct for MapSerializer<'a, W> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+		let mut buf = Vec::new();
+		value.serialize(&mut Serializer::new(&mut buf))?;
+		self.entries.push((key.as_bytes().to_vec(), buf));
+		Ok(())
+	}
+
+	fn end(self) -> Result<()> {
+		self.finish()
+	}
+}
+
+impl<'a, W: Write> ser::SerializeStructVariant for MapSerializer<'a, W> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+		ser::SerializeStruct::serialize_field(self, key, value)
+	}
+
+	fn end(self) -> Result<()> {
+		self.finish()
+	}
+}
+
+fn write_sorted_dict(out: &mut impl Write, mut entries: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+	entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+	out.write_all(b"d")?;
+	for (key, value) in entries {
+		write_string(&key, out)?;
+		out.write_all(&value)?;
+	}
+	out.write_all(b"e")?;
+	Ok(())
+}
+
+/// Serializes only the scalar types bencode can use as a dict key (strings
+/// and anything string-like); anything else is a programmer error, since
+/// bencode dict keys are always byte strings.
+struct MapKeySerializer<'a> {
+	out: &'a mut Vec<u8>,
+}
+
+impl<'a, 'b> ser::Serializer for &'b mut MapKeySerializer<'a> {
+	type Ok = ();
+	type Error = Error;
+	type SerializeSeq = ser::Impossible<(), Error>;
+	type SerializeTuple = ser::Impossible<(), Error>;
+	type SerializeTupleStruct = ser::Impossible<(), Error>;
+	type SerializeTupleVariant = ser::Impossible<(), Error>;
+	type SerializeMap = ser::Impossible<(), Error>;
+	type SerializeStruct = ser::Impossible<(), Error>;
+	type SerializeStructVariant = ser::Impossible<(), Error>;
+
+	fn serialize_str(self, v: &str) -> Result<()> {
+		self.out.extend_from_slice(v.as_bytes());
+		Ok(())
+	}
+
+	fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+		self.out.extend_from_slice(v);
+		Ok(())
+	}
+
+	fn serialize_bool(self, _v: bool) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_i8(self, _v: i8) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_i16(self, _v: i16) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_i32(self, _v: i32) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_i64(self, _v: i64) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_u8(self, _v: u8) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_u16(self, _v: u16) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_u32(self, _v: u32) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_u64(self, _v: u64) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_f32(self, _v: f32) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_f64(self, _v: f64) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_char(self, v: char) -> Result<()> {
+		self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
+	}
+	fn serialize_none(self) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
+		value.serialize(self)
+	}
+	fn serialize_unit(self) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<()> {
+		self.serialize_str(variant)
+	}
+	fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<()> {
+		value.serialize(self)
+	}
+	fn serialize_newtype_variant<T: ?Sized + Serialize>(
+		self,
+		_name: &'static str,
+		_index: u32,
+		_variant: &'static str,
+		_value: &T,
+	) -> Result<()> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_tuple_variant(
+		self,
+		_name: &'static str,
+		_index: u32,
+		_variant: &'static str,
+		_len: usize,
+	) -> Result<Self::SerializeTupleVariant> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+	fn serialize_struct_variant(
+		self,
+		_name: &'static str,
+		_index: u32,
+		_variant: &'static str,
+		_len: usize,
+	) -> Result<Self::SerializeStructVariant> {
+		Err(Error::Custom("map keys must be strings".to_string()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde::{Deserialize, Serialize};
+	use std::collections::HashMap;
+
+	#[test]
+	fn test_roundtrip_integer() {
+		let bytes = to_bytes(&-123i64).unwrap();
+		assert_eq!(bytes, b"i-123e");
+		assert_eq!(from_bytes::<i64>(&bytes).unwrap(), -123);
+	}
+
+	#[test]
+	fn test_roundtrip_string() {
+		let bytes = to_bytes(&String::from("spam")).unwrap();
+		assert_eq!(bytes, b"4:spam");
+		assert_eq!(from_bytes::<String>(&bytes).unwrap(), "spam");
+	}
+
+	#[test]
+	fn test_roundtrip_seq() {
+		let bytes = to_bytes(&vec![1i32, 2, 3]).unwrap();
+		assert_eq!(bytes, b"li1ei2ei3ee");
+		assert_eq!(from_bytes::<Vec<i32>>(&bytes).unwrap(), vec![1, 2, 3]);
+	}
+
+	#[derive(Debug, PartialEq, Serialize, Deserialize)]
+	struct Spam {
+		foo: i32,
+		cow: i32,
+	}
+
+	#[test]
+	fn test_struct_sorts_keys_like_write_dict() {
+		// Mirrors `test_write_value_dict_sorts_keys`: "cow" sorts before "foo".
+		let bytes = to_bytes(&Spam { foo: 1, cow: 2 }).unwrap();
+		assert_eq!(bytes, b"d3:cowi2e3:fooi1ee");
+		assert_eq!(from_bytes::<Spam>(&bytes).unwrap(), Spam { foo: 1, cow: 2 });
+	}
+
+	#[test]
+	fn test_roundtrip_map() {
+		let mut map = HashMap::new();
+		map.insert(String::from("a"), 1i32);
+		map.insert(String::from("b"), 2i32);
+		let bytes = to_bytes(&map).unwrap();
+		assert_eq!(from_bytes::<HashMap<String, i32>>(&bytes).unwrap(), map);
+	}
+
+	#[derive(Debug, PartialEq, Serialize, Deserialize)]
+	enum Mode {
+		Single { length: i64 },
+		Multi { files: Vec<i64> },
+	}
+
+	#[test]
+	fn test_struct_variant_roundtrips_as_wrapping_dict() {
+		let mode = Mode::Single { length: 5 };
+		let bytes = to_bytes(&mode).unwrap();
+		assert_eq!(bytes, b"d6:Singled6:lengthi5eee");
+		assert_eq!(from_bytes::<Mode>(&bytes).unwrap(), mode);
+	}
+
+	#[test]
+	fn test_unit_variant_roundtrips_as_string() {
+		#[derive(Debug, PartialEq, Serialize, Deserialize)]
+		enum Color {
+			Red,
+			Blue,
+		}
+
+		let bytes = to_bytes(&Color::Red).unwrap();
+		assert_eq!(bytes, b"3:Red");
+		assert_eq!(from_bytes::<Color>(&bytes).unwrap(), Color::Red);
+	}
+
+	#[test]
+	fn test_serialize_none_errors() {
+		// Bencode has no null, so an un-skipped `None` is a hard error
+		// rather than silently dropping the field.
+		let err = to_bytes(&None::<i32>).unwrap_err();
+		assert!(err.is_custom());
+	}
+
+	#[test]
+	fn test_deserialize_missing_field_errors() {
+		let err = from_bytes::<Spam>(b"d3:fooi1ee").unwrap_err();
+		assert!(err.is_custom());
+	}
+}